@@ -22,20 +22,220 @@ use core::{
     num::NonZeroU16,
 };
 
+/// Number of `u32` words in the enable bitmap for a single context.
+const ENABLE_WORDS: usize = 32;
+
+/// Number of `u32` words spanning the 1024 real interrupt sources in the pending bitmap.
+///
+/// `RegisterBlock::pending` has 128 words to fill out its reserved MMIO page, but only the
+/// first 32 correspond to sources 0..=1023.
+const PENDING_WORDS: usize = 32;
+
 /// Platform-Level Interrupt Controller
 pub struct Plic<const P: usize, const B: usize>(pub(crate) ());
 
 impl<const P: usize, const B: usize> Plic<P, B> {
     const PTR: *const plic::RegisterBlock = P as *const _;
+
+    /// Returns a runtime-addressed view of this PLIC, so the const-generic type can share its
+    /// register access code with [`PlicRef`].
+    #[inline]
+    fn as_ref() -> PlicRef {
+        // Safety: `P` is the MMIO base address this type was instantiated with, and `B`
+        // priority bits is the width it was instantiated with.
+        unsafe { PlicRef::new(Self::PTR, B as u8) }
+    }
 }
 
 impl<const P: usize, const B: usize> Plic<P, B> {
     /// Check if interrupt is enabled for context
     #[inline]
     pub fn is_enabled(context: usize, interrupt: impl Into<Nr>) -> bool {
+        Self::as_ref().is_enabled(context, interrupt)
+    }
+
+    /// Enable interrupt for context
+    ///
+    /// # Unsafety
+    ///
+    /// This function is unsafe because it can break mask-based critical sections
+    #[inline]
+    pub unsafe fn unmask(context: usize, interrupt: impl Into<Nr>) {
+        Self::as_ref().unmask(context, interrupt)
+    }
+
+    /// Disable interrupt for context
+    #[inline]
+    pub fn mask(context: usize, interrupt: impl Into<Nr>) {
+        Self::as_ref().mask(context, interrupt)
+    }
+
+    /// Get interrupt priority
+    #[inline]
+    pub fn get_priority(interrupt: impl Into<Nr>) -> Priority<B> {
+        Priority::from_bits(Self::as_ref().get_priority(interrupt))
+    }
+
+    /// Set interrupt priority
+    ///
+    /// # Unsafety
+    ///
+    /// Changing priority levels can break priority-based critical sections
+    /// and compromise memory safety.
+    #[inline]
+    pub unsafe fn set_priority(interrupt: impl Into<Nr>, prio: Priority<B>) {
+        Self::as_ref().set_priority(interrupt, prio.into_bits())
+    }
+
+    /// Get threshold for context
+    #[inline]
+    pub fn get_threshold(context: usize) -> Priority<B> {
+        Priority::from_bits(Self::as_ref().get_threshold(context))
+    }
+
+    /// Set threshold for context
+    #[inline]
+    pub unsafe fn set_threshold(context: usize, threshold: Priority<B>) {
+        Self::as_ref().set_threshold(context, threshold.into_bits())
+    }
+
+    /// Claim interrupt (used by interrupt runtime)
+    #[inline]
+    pub fn claim(context: usize) -> Option<Nr> {
+        Self::as_ref().claim(context)
+    }
+
+    /// Complete interrupt (used by interrupt runtime)
+    #[inline]
+    pub fn complete(context: usize, interrupt: impl Into<Nr>) {
+        Self::as_ref().complete(context, interrupt)
+    }
+
+    /// Checks if interrupt is pending
+    #[inline]
+    pub fn is_pending(interrupt: impl Into<Nr>) -> bool {
+        Self::as_ref().is_pending(interrupt)
+    }
+
+    /// Runs `f` as a priority-ceiling critical section on `context`.
+    ///
+    /// Raises the context threshold to `ceiling` for the duration of `f`, which excludes any
+    /// interrupt at or below that priority from firing on this context, and restores the
+    /// previous threshold afterwards — even if `f` panics. This is the safe counterpart to
+    /// hand-rolling `get_threshold`/`set_threshold` to build a priority-based critical section.
+    #[inline]
+    pub fn with_ceiling<T>(context: usize, ceiling: Priority<B>, f: impl FnOnce() -> T) -> T {
+        let _guard = PriorityGuard::<P, B>::new(context, ceiling);
+        f()
+    }
+
+    /// Claims and dispatches every interrupt currently pending for `context`.
+    ///
+    /// Repeatedly claims the highest-priority pending interrupt, looks it up in `handlers`,
+    /// invokes the matching handler, and completes it, until `claim` reports nothing left to
+    /// service. This is the vectored RISC-V interrupt pattern: the controller surfaces the
+    /// highest-priority pending source and the runtime fans out to the matching routine.
+    /// `complete` is issued exactly once per claim, even if a handler re-enables nested
+    /// interrupts; interrupt numbers absent from `handlers` are completed without being
+    /// dispatched.
+    pub fn handle_all(context: usize, handlers: &[(Nr, fn())]) {
+        while let Some(nr) = Self::claim(context) {
+            if let Some((_, handler)) = handlers.iter().find(|(entry, _)| *entry == nr) {
+                handler();
+            }
+            Self::complete(context, nr);
+        }
+    }
+
+    /// Disables every interrupt source for `context`.
+    #[inline]
+    pub fn disable_all(context: usize) {
+        for word in 0..ENABLE_WORDS {
+            unsafe { (*Self::PTR).enables[context].enable[word].write(0) };
+        }
+    }
+
+    /// Enables every interrupt source for `context`.
+    ///
+    /// # Unsafety
+    ///
+    /// This function is unsafe because it can break mask-based critical sections
+    #[inline]
+    pub unsafe fn enable_all(context: usize) {
+        for word in 0..ENABLE_WORDS {
+            (*Self::PTR).enables[context].enable[word].write(u32::MAX);
+        }
+    }
+
+    /// Saves the enable bitmap for `context`, e.g. before switching it out for another task.
+    #[inline]
+    pub fn save_enables(context: usize) -> [u32; ENABLE_WORDS] {
+        let mut saved = [0; ENABLE_WORDS];
+        for (word, slot) in saved.iter_mut().enumerate() {
+            *slot = unsafe { (*Self::PTR).enables[context].enable[word].read() };
+        }
+        saved
+    }
+
+    /// Restores an enable bitmap previously returned by [`save_enables`](Self::save_enables).
+    ///
+    /// # Unsafety
+    ///
+    /// This function is unsafe because it can break mask-based critical sections
+    #[inline]
+    pub unsafe fn restore_enables(context: usize, saved: &[u32; ENABLE_WORDS]) {
+        for (word, bits) in saved.iter().enumerate() {
+            (*Self::PTR).enables[context].enable[word].write(*bits);
+        }
+    }
+
+    /// Iterates over every interrupt source currently marked pending, walking the pending
+    /// bitmap word-by-word.
+    pub fn pending_iter() -> impl Iterator<Item = Nr> {
+        (0..PENDING_WORDS).flat_map(|word| {
+            let bits = unsafe { (*Self::PTR).pending[word].read() };
+            (0..32u16).filter_map(move |bit| {
+                if bits & (1 << bit) != 0 {
+                    Nr::try_from(word as u16 * 32 + bit).ok()
+                } else {
+                    None
+                }
+            })
+        })
+    }
+}
+
+/// Platform-Level Interrupt Controller, addressed by a runtime-known base and priority width.
+///
+/// Unlike [`Plic`], which bakes its MMIO base address and priority-bit width into the type via
+/// const generics, `PlicRef` takes both at construction time. Use this when the base address
+/// and priority width are only known at boot, e.g. parsed from a device tree, or when a
+/// platform must manage more than one PLIC instance.
+#[derive(Clone, Copy)]
+pub struct PlicRef {
+    base: *const plic::RegisterBlock,
+    priority_bits: u8,
+}
+
+impl PlicRef {
+    /// Creates a `PlicRef` for the PLIC whose register block starts at `base`, with a priority
+    /// field `priority_bits` bits wide.
+    ///
+    /// # Unsafety
+    ///
+    /// `base` must point to a valid PLIC register block that lives for as long as this
+    /// `PlicRef`, or any copy of it, is used.
+    #[inline]
+    pub const unsafe fn new(base: *const plic::RegisterBlock, priority_bits: u8) -> Self {
+        PlicRef { base, priority_bits }
+    }
+
+    /// Check if interrupt is enabled for context
+    #[inline]
+    pub fn is_enabled(&self, context: usize, interrupt: impl Into<Nr>) -> bool {
         let irq_number = interrupt.into().index() as usize;
         unsafe {
-            (*Self::PTR).enables[context].enable[irq_number / 32].read() & 1 << (irq_number % 32)
+            (*self.base).enables[context].enable[irq_number / 32].read() & 1 << (irq_number % 32)
                 != 0
         }
     }
@@ -46,78 +246,220 @@ impl<const P: usize, const B: usize> Plic<P, B> {
     ///
     /// This function is unsafe because it can break mask-based critical sections
     #[inline]
-    pub unsafe fn unmask(context: usize, interrupt: impl Into<Nr>) {
+    pub unsafe fn unmask(&self, context: usize, interrupt: impl Into<Nr>) {
         let irq_number = interrupt.into().index() as usize;
-        (*Self::PTR).enables[context].enable[irq_number / 32]
+        (*self.base).enables[context].enable[irq_number / 32]
             .modify(|v| v | 1 << (irq_number % 32));
     }
 
     /// Disable interrupt for context
     #[inline]
-    pub fn mask(context: usize, interrupt: impl Into<Nr>) {
+    pub fn mask(&self, context: usize, interrupt: impl Into<Nr>) {
         let irq_number = interrupt.into().index() as usize;
         unsafe {
-            (*Self::PTR).enables[context].enable[irq_number / 32]
+            (*self.base).enables[context].enable[irq_number / 32]
                 .modify(|v| v & !(1 << (irq_number % 32)));
         }
     }
 
-    /// Get interrupt priority
+    /// Get interrupt priority, as raw bits in `0..=(2 << priority_bits) - 1`.
     #[inline]
-    pub fn get_priority(interrupt: impl Into<Nr>) -> Priority<B> {
+    pub fn get_priority(&self, interrupt: impl Into<Nr>) -> u32 {
         let irq_number = interrupt.into().index() as usize;
-        let bits = unsafe { (*Self::PTR).priority[irq_number].read() };
-        Priority::from_bits(bits)
+        unsafe { (*self.base).priority[irq_number].read() }
     }
 
-    /// Set interrupt priority
+    /// Set interrupt priority to `prio`, a raw value in `0..=(2 << priority_bits) - 1`.
     ///
     /// # Unsafety
     ///
     /// Changing priority levels can break priority-based critical sections
     /// and compromise memory safety.
     #[inline]
-    pub unsafe fn set_priority(interrupt: impl Into<Nr>, prio: Priority<B>) {
+    pub unsafe fn set_priority(&self, interrupt: impl Into<Nr>, prio: u32) {
+        debug_assert!(self.is_valid_priority(prio), "invalid priority");
         let irq_number = interrupt.into().index() as usize;
-        (*Self::PTR).priority[irq_number].write(prio.into_bits());
+        (*self.base).priority[irq_number].write(prio);
     }
 
-    /// Get threshold for context
+    /// Get threshold for context, as raw bits in `0..=(2 << priority_bits) - 1`.
     #[inline]
-    pub fn get_threshold(context: usize) -> Priority<B> {
-        let bits = unsafe { (*Self::PTR).contexts[context].threshold.read() };
-        Priority::from_bits(bits)
+    pub fn get_threshold(&self, context: usize) -> u32 {
+        unsafe { (*self.base).contexts[context].threshold.read() }
     }
 
-    /// Set threshold for context
+    /// Set threshold for context to `threshold`, a raw value in `0..=(2 << priority_bits) - 1`.
+    ///
+    /// # Unsafety
+    ///
+    /// Changing priority levels can break priority-based critical sections
+    /// and compromise memory safety.
     #[inline]
-    pub unsafe fn set_threshold(context: usize, threshold: Priority<B>) {
-        (*Self::PTR).contexts[context]
-            .threshold
-            .write(threshold.into_bits());
+    pub unsafe fn set_threshold(&self, context: usize, threshold: u32) {
+        debug_assert!(self.is_valid_priority(threshold), "invalid priority");
+        (*self.base).contexts[context].threshold.write(threshold);
+    }
+
+    /// Checks whether `prio` is in range for this PLIC's priority width, mirroring
+    /// [`Priority::highest`].
+    #[inline]
+    fn is_valid_priority(&self, prio: u32) -> bool {
+        self.priority_bits == 32 || prio < (2u32 << self.priority_bits)
     }
 
     /// Claim interrupt (used by interrupt runtime)
     #[inline]
-    pub fn claim(context: usize) -> Option<Nr> {
-        let bits = unsafe { (*Self::PTR).contexts[context].claim.read() };
+    pub fn claim(&self, context: usize) -> Option<Nr> {
+        let bits = unsafe { (*self.base).contexts[context].claim.read() };
         <Nr as TryFrom<u32>>::try_from(bits).ok()
     }
 
     /// Complete interrupt (used by interrupt runtime)
     #[inline]
-    pub fn complete(context: usize, interrupt: impl Into<Nr>) {
+    pub fn complete(&self, context: usize, interrupt: impl Into<Nr>) {
         let irq_number = interrupt.into().index() as u32;
         unsafe {
-            (*Self::PTR).contexts[context].claim.write(irq_number);
+            (*self.base).contexts[context].claim.write(irq_number);
         }
     }
 
     /// Checks if interrupt is pending
     #[inline]
-    pub fn is_pending(interrupt: impl Into<Nr>) -> bool {
+    pub fn is_pending(&self, interrupt: impl Into<Nr>) -> bool {
         let irq_number = interrupt.into().index() as usize;
-        unsafe { (*Self::PTR).pending[irq_number / 32].read() & 1 << (irq_number % 32) != 0 }
+        unsafe { (*self.base).pending[irq_number / 32].read() & 1 << (irq_number % 32) != 0 }
+    }
+}
+
+// Safety: all access to the register block is through volatile reads/writes, same as `Plic`.
+unsafe impl Send for PlicRef {}
+unsafe impl Sync for PlicRef {}
+
+/// A generic interrupt controller abstraction.
+///
+/// Implemented by [`Plic`] so a dispatch runtime can be written once against this trait and
+/// reused for PLIC or any other controller exposing the same claim/complete/enable/priority
+/// operations.
+pub trait InterruptController {
+    /// The interrupt number type returned by [`claim`](InterruptController::claim).
+    type Interrupt;
+    /// The priority level type used by threshold operations.
+    type Priority;
+
+    /// Claims the highest-priority pending interrupt for `context`, if any.
+    fn claim(context: usize) -> Option<Self::Interrupt>;
+    /// Signals completion of `interrupt` on `context`.
+    fn complete(context: usize, interrupt: Self::Interrupt);
+    /// Enables `interrupt` for `context`.
+    ///
+    /// # Unsafety
+    ///
+    /// This function is unsafe because it can break mask-based critical sections
+    unsafe fn unmask(context: usize, interrupt: Self::Interrupt);
+    /// Disables `interrupt` for `context`.
+    fn mask(context: usize, interrupt: Self::Interrupt);
+    /// Gets the priority threshold for `context`.
+    fn get_threshold(context: usize) -> Self::Priority;
+    /// Sets the priority threshold for `context`.
+    ///
+    /// # Unsafety
+    ///
+    /// Changing priority levels can break priority-based critical sections
+    /// and compromise memory safety.
+    unsafe fn set_threshold(context: usize, threshold: Self::Priority);
+}
+
+impl<const P: usize, const B: usize> InterruptController for Plic<P, B> {
+    type Interrupt = Nr;
+    type Priority = Priority<B>;
+
+    #[inline]
+    fn claim(context: usize) -> Option<Nr> {
+        Self::claim(context)
+    }
+    #[inline]
+    fn complete(context: usize, interrupt: Nr) {
+        Self::complete(context, interrupt)
+    }
+    #[inline]
+    unsafe fn unmask(context: usize, interrupt: Nr) {
+        Self::unmask(context, interrupt)
+    }
+    #[inline]
+    fn mask(context: usize, interrupt: Nr) {
+        Self::mask(context, interrupt)
+    }
+    #[inline]
+    fn get_threshold(context: usize) -> Priority<B> {
+        Self::get_threshold(context)
+    }
+    #[inline]
+    unsafe fn set_threshold(context: usize, threshold: Priority<B>) {
+        Self::set_threshold(context, threshold)
+    }
+}
+
+/// Runs `body`, then claims and dispatches every interrupt controller `C` reports pending for
+/// `context`, routing each claimed interrupt to its matching entry in `handlers`, or to
+/// `default_handler` if none matches.
+///
+/// This is the generic, trait-based analogue of [`Plic::handle_all`]: it drives the
+/// claim/complete loop itself via [`InterruptController`], so a dispatch runtime written once
+/// against the trait gets a portable way to register per-interrupt handlers instead of wiring
+/// `claim`/`complete` by hand for whichever controller `C` happens to be.
+#[allow(clippy::type_complexity)]
+pub fn scope<C, R>(
+    context: usize,
+    handlers: &[(C::Interrupt, fn())],
+    default_handler: fn(C::Interrupt),
+    body: impl FnOnce() -> R,
+) -> R
+where
+    C: InterruptController,
+    C::Interrupt: Copy + PartialEq,
+{
+    let result = body();
+    while let Some(nr) = C::claim(context) {
+        match handlers.iter().find(|(entry, _)| *entry == nr) {
+            Some((_, handler)) => handler(),
+            None => default_handler(nr),
+        }
+        C::complete(context, nr);
+    }
+    result
+}
+
+/// RAII guard implementing the priority-ceiling protocol for a PLIC context.
+///
+/// While the guard is alive, the context's threshold is raised to the ceiling it was created
+/// with, excluding interrupts at or below that priority from firing on the context. Dropping
+/// the guard restores the threshold that was in effect when the guard was created.
+pub struct PriorityGuard<const P: usize, const B: usize> {
+    context: usize,
+    saved: Priority<B>,
+}
+
+impl<const P: usize, const B: usize> PriorityGuard<P, B> {
+    /// Enters a priority-ceiling critical section on `context`, raising its threshold to
+    /// `ceiling`.
+    #[inline]
+    pub fn new(context: usize, ceiling: Priority<B>) -> Self {
+        let saved = Plic::<P, B>::get_threshold(context);
+        unsafe { Plic::<P, B>::set_threshold(context, ceiling) };
+        // Read the threshold back so the MMIO store is globally visible before any
+        // protected code runs.
+        Plic::<P, B>::get_threshold(context);
+        PriorityGuard { context, saved }
+    }
+}
+
+impl<const P: usize, const B: usize> Drop for PriorityGuard<P, B> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { Plic::<P, B>::set_threshold(self.context, self.saved) };
+        // Read the threshold back so the restore completes before lower-priority
+        // interrupts can fire.
+        Plic::<P, B>::get_threshold(self.context);
     }
 }
 